@@ -3,6 +3,7 @@
 //! [dependencies]
 //! serde = { version = "1.0", features = ["derive"] }
 //! serde_json = "1.0"
+//! rmp-serde = "1.1"
 //! reqwest = { version = "0.11", features = ["blocking", "json"] }
 //! uuid = { version = "1.0", features = ["v4"] }
 //! rand = "0.8"
@@ -11,14 +12,19 @@
 use std::env;
 use std::io::{self, Read};
 use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::Value;
 use uuid::Uuid;
 use rand::Rng;
 
+// `event`/`timestamp` mirror the hook's stdin schema but aren't consumed
+// downstream yet; kept so the struct documents the full input shape.
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 struct ToolData {
     tool_name: String,
@@ -29,14 +35,14 @@ struct ToolData {
     tool_call_id: Option<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct AgUIEvent {
     #[serde(rename = "type")]
     event_type: String,
     data: EventData,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct EventData {
     #[serde(rename = "toolCallId")]
     tool_call_id: String,
@@ -50,9 +56,134 @@ struct EventData {
     tool_output: Option<Value>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct BatchPayload {
+    events: Vec<AgUIEvent>,
+}
+
+/// A workload for `bench`: a named list of synthetic tool-call records plus
+/// the knobs that would normally come from the hook's environment variables.
+/// Unset `encoding` and `endpoint` fall back to `ALPINE_EVENTS_ENCODING` and
+/// `ALPINE_EVENTS_ENDPOINT`; unset `batch_size` and `sample_rate` default to 1
+/// and 100 and do not read their `ALPINE_*` env vars.
+#[derive(Debug, Deserialize)]
+struct Workload {
+    name: Option<String>,
+    events: Vec<ToolData>,
+    target_qps: Option<f64>,
+    batch_size: Option<usize>,
+    sample_rate: Option<u32>,
+    encoding: Option<String>,
+    endpoint: Option<String>,
+    results_endpoint: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchResult {
+    workload: String,
+    count: usize,
+    p50_ms: f64,
+    p95_ms: f64,
+    p99_ms: f64,
+    bytes_sent: u64,
+    failures: usize,
+    circuit_breaker_trips: usize,
+}
+
+/// A single framed record in a run's durable event log.
+///
+/// `RunFinished` is the terminal sentinel: once the flusher has read and
+/// acknowledged it, the run's log is fully drained and can be compacted away.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+enum LogRecord {
+    Event(AgUIEvent),
+    RunFinished,
+}
+
+/// Wire format for outgoing events, selected via `ALPINE_EVENTS_ENCODING`.
+///
+/// `Json` is the interoperable default; `MsgPack` trades that off for smaller
+/// payloads and cheaper (de)serialization on high-volume runs. Bincode was
+/// dropped as an option here: it isn't self-describing, so it can't decode
+/// the `Option<serde_json::Value>` fields in `EventData` (`tool_input`/
+/// `tool_output` are arbitrary caller-supplied JSON) and would silently lose
+/// every event written under it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Json,
+    MsgPack,
+}
+
+impl Encoding {
+    fn from_name(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
+            "msgpack" => Encoding::MsgPack,
+            _ => Encoding::Json,
+        }
+    }
+
+    fn from_env() -> Self {
+        Encoding::from_name(&env::var("ALPINE_EVENTS_ENCODING").unwrap_or_else(|_| "json".to_string()))
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::MsgPack => "application/msgpack",
+        }
+    }
+
+    /// Binary encodings use length-prefixed frames for the batch file; JSON
+    /// keeps the existing NDJSON layout.
+    fn is_binary(&self) -> bool {
+        !matches!(self, Encoding::Json)
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match self {
+            Encoding::Json => Ok(serde_json::to_vec(value)?),
+            // rmp_serde's plain `to_vec` encodes structs positionally (as a
+            // fixed-length array), so `skip_serializing_if` on an Option
+            // field changes the element count and the struct becomes
+            // undecodable. `with_struct_map()` encodes structs as maps
+            // instead, keyed by field name, so omitted optional fields are
+            // just absent keys rather than a length mismatch.
+            Encoding::MsgPack => {
+                let mut buf = Vec::new();
+                value.serialize(&mut rmp_serde::Serializer::new(&mut buf).with_struct_map())?;
+                Ok(buf)
+            }
+        }
+    }
+
+    fn decode<T: for<'de> Deserialize<'de>>(&self, bytes: &[u8]) -> Result<T, Box<dyn std::error::Error>> {
+        match self {
+            Encoding::Json => Ok(serde_json::from_slice(bytes)?),
+            Encoding::MsgPack => Ok(rmp_serde::from_slice(bytes)?),
+        }
+    }
+}
+
 fn main() -> io::Result<()> {
-    // Check circuit breaker first
-    if is_circuit_breaker_open() {
+    // `--flush <run_id>` and `--finish <run_id>` drive the durable event log
+    // rather than processing a tool-call hook invocation from stdin.
+    let args: Vec<String> = env::args().collect();
+    match (args.get(1).map(String::as_str), args.get(2)) {
+        (Some("--flush"), Some(run_id)) => return run_flusher(run_id),
+        (Some("--finish"), Some(run_id)) => return finish_run(run_id),
+        _ => {}
+    }
+    if args.get(1).map(String::as_str) == Some("bench") {
+        return run_bench(&args[2..]);
+    }
+
+    // Check circuit breaker first. This only decides whether to bother
+    // spooling the event at all - it must never claim or resolve the
+    // Half-Open trial, since the flusher is the only process that ever
+    // actually sends (see run_flusher). A hook invocation that won the trial
+    // here would never send anything and never call record_success/
+    // record_failure, squatting the lock until it went stale again.
+    if is_circuit_breaker_blocking_spool() {
         eprintln!("Circuit breaker is open, skipping hook execution");
         return Ok(());
     }
@@ -60,7 +191,7 @@ fn main() -> io::Result<()> {
     // Read tool data from stdin
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
-    
+
     // Parse the JSON data
     let tool_data: ToolData = match serde_json::from_str(&input) {
         Ok(data) => data,
@@ -70,29 +201,24 @@ fn main() -> io::Result<()> {
             return Ok(()); // Don't fail the hook
         }
     };
-    
+
     // Log that hook was called
     eprintln!("HOOK CALLED: tool={}", tool_data.tool_name);
-    
-    // Get environment variables
-    let endpoint = match env::var("ALPINE_EVENTS_ENDPOINT") {
-        Ok(val) => val,
-        Err(_) => {
-            eprintln!("ALPINE_EVENTS_ENDPOINT not set, skipping event emission");
-            return Ok(());
-        }
-    };
-    
+
+    // The flusher (spawned below) reads ALPINE_EVENTS_ENDPOINT itself from the
+    // inherited environment, so the hook only needs to check it's present.
+    if env::var("ALPINE_EVENTS_ENDPOINT").is_err() {
+        eprintln!("ALPINE_EVENTS_ENDPOINT not set, skipping event emission");
+        return Ok(());
+    }
+
     let run_id = env::var("ALPINE_RUN_ID").unwrap_or_else(|_| "unknown".to_string());
-    let batch_size: usize = env::var("ALPINE_TOOL_CALL_BATCH_SIZE")
-        .unwrap_or_else(|_| "10".to_string())
-        .parse()
-        .unwrap_or(10);
     let sample_rate: u32 = env::var("ALPINE_TOOL_CALL_SAMPLE_RATE")
         .unwrap_or_else(|_| "100".to_string())
         .parse()
         .unwrap_or(100);
-    
+    let encoding = Encoding::from_env();
+
     // Apply sampling - skip event if random number is above sample rate
     if sample_rate < 100 {
         let mut rng = rand::thread_rng();
@@ -102,18 +228,18 @@ fn main() -> io::Result<()> {
             return Ok(());
         }
     }
-    
+
     // Generate or use existing tool call ID
     let tool_call_id = tool_data.tool_call_id
         .unwrap_or_else(|| Uuid::new_v4().to_string());
-    
+
     // Determine event type based on whether we have tool output
     let event_type = if tool_data.tool_output.is_some() {
         "ToolCallEnd"
     } else {
         "ToolCallStart"
     };
-    
+
     // Create event
     let event = AgUIEvent {
         event_type: event_type.to_string(),
@@ -125,118 +251,944 @@ fn main() -> io::Result<()> {
             tool_output: if event_type == "ToolCallEnd" { tool_data.tool_output } else { None },
         },
     };
-    
-    // Handle batching with error handling
-    let result = if batch_size > 1 {
-        add_to_batch(&event, batch_size, &endpoint)
-            .or_else(|e| {
-                eprintln!("Failed to add event to batch: {}, trying direct send", e);
-                send_event(&endpoint, &event)
-            })
-    } else {
-        send_event(&endpoint, &event)
-    };
 
-    match result {
-        Ok(_) => {
-            record_success();
-            eprintln!("Event sent successfully");
-        }
-        Err(e) => {
-            eprintln!("Failed to send event: {}", e);
-            record_failure();
-            // Don't fail the hook - workflow should continue
-        }
+    // The durable log is the sole delivery path: append the record and make
+    // sure a flusher is running to drain it. The hook itself never also sends
+    // the event directly - doing both used to mean every event was delivered
+    // twice (once here, once again when the flusher tailed the record it had
+    // just read back out of the log).
+    if let Err(e) = append_record(&run_id, &LogRecord::Event(event.clone()), encoding) {
+        eprintln!("Failed to append event to durable log: {}", e);
     }
-    
+    ensure_flusher_running(&run_id);
+    eprintln!("Event queued for delivery (run_id={})", run_id);
+
     Ok(())
 }
 
-fn add_to_batch(event: &AgUIEvent, batch_size: usize, endpoint: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let batch_file = "/tmp/alpine_event_batch.json";
-    
+type SendResult = Result<(), Box<dyn std::error::Error>>;
+
+/// Outcome of trying to flush a batch (whether from `add_to_batch` reaching
+/// `batch_size` or `flush_remaining_batch` draining a trailing partial one).
+///
+/// The breaker is only ever claimed and resolved at the moment an actual
+/// network send is attempted (`Sent`), never just for queueing an event into
+/// a not-yet-full batch (`NotSent`) - claiming it earlier than that would
+/// squat the Half-Open trial on an iteration that never calls
+/// `record_success`/`record_failure` to release it. `Blocked` means a batch
+/// was ready to go out but the breaker was already open, so nothing was
+/// sent; the events are left queued on disk rather than dropped.
+enum BatchOutcome {
+    NotSent,
+    Sent(SendResult),
+    Blocked,
+}
+
+/// Per-key batch file path so concurrent callers accumulating under
+/// different keys (e.g. separate `bench` workloads run in one invocation)
+/// don't share - and corrupt - the same on-disk batch.
+fn batch_file_for(batch_key: &str) -> String {
+    format!("/tmp/alpine_event_batch_{}.json", sanitize_run_id(batch_key))
+}
+
+/// Accumulates `event` into `batch_key`'s batch file, sending it once it
+/// reaches `batch_size`. Callers must only record a success or failure on
+/// `BatchOutcome::Sent`: counting `NotSent` either way would fabricate a
+/// delivery that never happened, and `Blocked` is a breaker rejection, not a
+/// send attempt.
+fn add_to_batch(
+    batch_key: &str,
+    event: &AgUIEvent,
+    batch_size: usize,
+    endpoint: &str,
+    encoding: Encoding,
+) -> Result<BatchOutcome, Box<dyn std::error::Error>> {
+    let batch_file = batch_file_for(batch_key);
+
     // Read existing batch or create new one
-    let mut events: Vec<AgUIEvent> = if Path::new(batch_file).exists() {
-        let file = File::open(batch_file)?;
-        let reader = BufReader::new(file);
-        let mut batch_events = Vec::new();
-        
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                if let Ok(event) = serde_json::from_str::<AgUIEvent>(&line) {
-                    batch_events.push(event);
-                }
-            }
-        }
-        batch_events
+    let mut events: Vec<AgUIEvent> = if Path::new(&batch_file).exists() {
+        read_batch_file(&batch_file, encoding)?
     } else {
         Vec::new()
     };
-    
+
     // Add current event to batch
     events.push(event.clone());
-    
+
     // Check if batch is full
     if events.len() >= batch_size {
-        // Send batch
-        if let Err(e) = send_batch(endpoint, &events) {
-            eprintln!("Failed to send batch: {}", e);
+        if is_circuit_breaker_open() {
+            write_batch_file(&batch_file, &events, encoding)?;
+            return Ok(BatchOutcome::Blocked);
         }
-        
-        // Clear batch file
-        std::fs::remove_file(batch_file).ok();
+        let result = send_batch(endpoint, &events, encoding);
+        std::fs::remove_file(&batch_file).ok();
+        Ok(BatchOutcome::Sent(result))
     } else {
-        // Write updated batch back to file
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(batch_file)?;
-        
-        for event in &events {
+        // Write updated batch back to file, mirroring the chosen encoding
+        write_batch_file(&batch_file, &events, encoding)?;
+        Ok(BatchOutcome::NotSent)
+    }
+}
+
+/// Sends and clears whatever is left in `batch_key`'s batch file once a
+/// workload finishes, so a trailing partial batch - one that never reached
+/// `batch_size` - is still delivered instead of silently left on disk.
+fn flush_remaining_batch(batch_key: &str, endpoint: &str, encoding: Encoding) -> BatchOutcome {
+    let batch_file = batch_file_for(batch_key);
+    if !Path::new(&batch_file).exists() {
+        return BatchOutcome::NotSent;
+    }
+
+    let events = match read_batch_file(&batch_file, encoding) {
+        Ok(events) if !events.is_empty() => events,
+        _ => {
+            std::fs::remove_file(&batch_file).ok();
+            return BatchOutcome::NotSent;
+        }
+    };
+
+    if is_circuit_breaker_open() {
+        return BatchOutcome::Blocked;
+    }
+
+    let result = send_batch(endpoint, &events, encoding);
+    std::fs::remove_file(&batch_file).ok();
+    BatchOutcome::Sent(result)
+}
+
+/// Reads back the on-disk batch in whatever framing `encoding` uses: NDJSON
+/// for `Json`, length-prefixed frames for the binary encodings.
+fn read_batch_file(batch_file: &str, encoding: Encoding) -> Result<Vec<AgUIEvent>, Box<dyn std::error::Error>> {
+    let mut events = Vec::new();
+
+    if encoding.is_binary() {
+        let mut file = File::open(batch_file)?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+
+        let mut cursor = 0usize;
+        while cursor + 4 <= buf.len() {
+            let len = u32::from_be_bytes(buf[cursor..cursor + 4].try_into()?) as usize;
+            cursor += 4;
+            if cursor + len > buf.len() {
+                break;
+            }
+            if let Ok(event) = encoding.decode::<AgUIEvent>(&buf[cursor..cursor + len]) {
+                events.push(event);
+            }
+            cursor += len;
+        }
+    } else {
+        let file = File::open(batch_file)?;
+        let reader = BufReader::new(file);
+
+        for line in reader.lines().map_while(Result::ok) {
+            if let Ok(event) = serde_json::from_str::<AgUIEvent>(&line) {
+                events.push(event);
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+fn write_batch_file(batch_file: &str, events: &[AgUIEvent], encoding: Encoding) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(batch_file)?;
+
+    if encoding.is_binary() {
+        for event in events {
+            let bytes = encoding.encode(event)?;
+            file.write_all(&(bytes.len() as u32).to_be_bytes())?;
+            file.write_all(&bytes)?;
+        }
+    } else {
+        for event in events {
             let event_json = serde_json::to_string(event)?;
             writeln!(file, "{}", event_json)?;
         }
     }
-    
+
     Ok(())
 }
 
-fn send_batch(endpoint: &str, events: &[AgUIEvent]) -> Result<(), Box<dyn std::error::Error>> {
+fn send_batch(endpoint: &str, events: &[AgUIEvent], encoding: Encoding) -> Result<(), Box<dyn std::error::Error>> {
     let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
+        .timeout(Duration::from_secs(10))
         .build()?;
-    
-    let batch_payload = json!({
-        "events": events
-    });
-    
-    let response = client
-        .post(endpoint)
-        .json(&batch_payload)
-        .send()?;
-    
-    if !response.status().is_success() {
-        return Err(format!("HTTP error: {}", response.status()).into());
-    }
-    
+
+    let batch_payload = BatchPayload { events: events.to_vec() };
+    let body = encoding.encode(&batch_payload)?;
+
+    with_retries(|| {
+        let response = client
+            .post(endpoint)
+            .header("Content-Type", encoding.content_type())
+            .body(body.clone())
+            .send()?;
+
+        if !response.status().is_success() {
+            return Err(format!("HTTP error: {}", response.status()).into());
+        }
+
+        Ok(())
+    })?;
+
     eprintln!("Sent batch of {} events", events.len());
     Ok(())
 }
 
-fn send_event(endpoint: &str, event: &AgUIEvent) -> Result<(), Box<dyn std::error::Error>> {
+fn send_event(endpoint: &str, event: &AgUIEvent, encoding: Encoding) -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()?;
+
+    let body = encoding.encode(event)?;
+
+    with_retries(|| {
+        let response = client
+            .post(endpoint)
+            .header("Content-Type", encoding.content_type())
+            .body(body.clone())
+            .send()?;
+
+        if !response.status().is_success() {
+            return Err(format!("HTTP error: {}", response.status()).into());
+        }
+
+        Ok(())
+    })
+}
+
+// --- Retry with exponential backoff ---------------------------------------
+//
+// Paired with the circuit breaker below: a send only counts as a `record_failure`
+// once retries are exhausted, so a single blip on a 5xx/timeout doesn't trip
+// the breaker on its own.
+
+const RETRY_MAX_ATTEMPTS: u32 = 4;
+const RETRY_BASE_DELAY_MS: u64 = 200;
+const RETRY_MAX_DELAY_MS: u64 = 5_000;
+
+fn with_retries<F>(mut attempt: F) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Result<(), Box<dyn std::error::Error>>,
+{
+    let mut delay_ms = RETRY_BASE_DELAY_MS;
+    let mut last_err: Option<Box<dyn std::error::Error>> = None;
+
+    for attempt_num in 0..RETRY_MAX_ATTEMPTS {
+        match attempt() {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                let retryable = is_retryable(e.as_ref());
+                last_err = Some(e);
+                if !retryable || attempt_num + 1 >= RETRY_MAX_ATTEMPTS {
+                    break;
+                }
+                let jitter = rand::thread_rng().gen_range(0..=(delay_ms / 2 + 1));
+                thread::sleep(Duration::from_millis(delay_ms + jitter));
+                delay_ms = (delay_ms * 2).min(RETRY_MAX_DELAY_MS);
+            }
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| "retry loop exhausted with no error".into()))
+}
+
+fn is_retryable(err: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(reqwest_err) = err.downcast_ref::<reqwest::Error>() {
+        return reqwest_err.is_timeout() || reqwest_err.is_connect();
+    }
+    err.to_string().starts_with("HTTP error: 5")
+}
+
+// --- Persistent three-state circuit breaker -------------------------------
+//
+// Closed: count consecutive failures; at ALPINE_CB_THRESHOLD, trip to Open.
+// Open: reject sends until ALPINE_CB_COOLDOWN_MS has elapsed, then move to
+// Half-Open. Half-Open: allow exactly one trial send (guarded by an
+// exclusive-create lock file, since each hook invocation is a separate
+// process); success resets to Closed, failure returns to Open. State is
+// shared across processes via a state file written atomically
+// (write-to-temp-then-rename) so concurrent hook invocations can't corrupt it.
+
+const CB_STATE_PATH: &str = "/tmp/alpine_circuit_breaker_state.json";
+const CB_TRIAL_LOCK_PATH: &str = "/tmp/alpine_circuit_breaker_trial.lock";
+// The reclaim guard (see `reclaim_stale_trial_lock`) is only ever held for a
+// few filesystem calls, not a full trial send, so it can use a much shorter
+// staleness bound than ALPINE_CB_COOLDOWN_MS.
+const CB_TRIAL_RECLAIM_GUARD_STALE_MS: u64 = 5_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CircuitBreakerData {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: u64,
+}
+
+impl Default for CircuitBreakerData {
+    fn default() -> Self {
+        CircuitBreakerData {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            opened_at: 0,
+        }
+    }
+}
+
+fn cb_threshold() -> u32 {
+    env::var("ALPINE_CB_THRESHOLD").ok().and_then(|s| s.parse().ok()).unwrap_or(5)
+}
+
+fn cb_cooldown_ms() -> u64 {
+    env::var("ALPINE_CB_COOLDOWN_MS").ok().and_then(|s| s.parse().ok()).unwrap_or(30_000)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn read_cb_state() -> CircuitBreakerData {
+    std::fs::read_to_string(CB_STATE_PATH)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn write_cb_state(data: &CircuitBreakerData) {
+    let tmp_path = format!("{}.tmp.{}", CB_STATE_PATH, std::process::id());
+    let Ok(serialized) = serde_json::to_string(data) else { return };
+    if std::fs::write(&tmp_path, serialized).is_ok() {
+        let _ = std::fs::rename(&tmp_path, CB_STATE_PATH);
+    }
+}
+
+/// Exactly one concurrent process may take the Half-Open trial send.
+///
+/// The lock file is stamped with the claim time. Hook invocations are
+/// short-lived and can be killed mid-trial, which would otherwise leave the
+/// lock in place forever and wedge the breaker in Half-Open indefinitely; a
+/// claim older than `cb_cooldown_ms()` is treated as abandoned and reclaimed.
+fn claim_half_open_trial() -> bool {
+    if try_create_trial_lock() {
+        return true;
+    }
+
+    if is_trial_lock_stale() {
+        reclaim_stale_trial_lock()
+    } else {
+        false
+    }
+}
+
+fn is_trial_lock_stale() -> bool {
+    std::fs::read_to_string(CB_TRIAL_LOCK_PATH)
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(|claimed_at| now_ms().saturating_sub(claimed_at) >= cb_cooldown_ms())
+        .unwrap_or(true) // missing or corrupt claim stamp: treat as abandoned
+}
+
+/// Creates the lock file and stamps it with the claim time. A write failure
+/// after the exclusive create (e.g. disk full) is not swallowed: it removes
+/// the just-created file and reports no claim, rather than leaving an empty
+/// lock file behind that `is_trial_lock_stale` would treat as immediately
+/// abandoned and let a second process reclaim out from under the first.
+fn try_create_trial_lock() -> bool {
+    match OpenOptions::new().write(true).create_new(true).open(CB_TRIAL_LOCK_PATH) {
+        Ok(mut file) => {
+            if file.write_all(now_ms().to_string().as_bytes()).is_ok() {
+                true
+            } else {
+                std::fs::remove_file(CB_TRIAL_LOCK_PATH).ok();
+                false
+            }
+        }
+        Err(_) => false,
+    }
+}
+
+/// Replaces an abandoned lock with our own claim.
+///
+/// Checking staleness and then removing-and-recreating the lock aren't one
+/// atomic step, so several processes can each see the same stale claim and
+/// all try to reclaim it at once. This serializes reclaim attempts through
+/// `CB_TRIAL_LOCK_PATH.reclaiming`, an exclusive-create guard using the same
+/// atomic primitive as the lock itself: only the process that creates the
+/// guard proceeds, and it re-checks staleness once inside (the lock may have
+/// been refreshed or released while it waited for the guard) before
+/// removing and recreating it. Everyone else simply loses this round's
+/// trial and can try again on their next invocation.
+///
+/// The guard itself is given the same staleness treatment (with a much
+/// shorter bound, since it's only ever held for a few filesystem calls): if
+/// whoever created it died before releasing it, a later caller reclaims the
+/// guard the same way the main lock gets reclaimed, instead of leaving the
+/// breaker permanently wedged behind a leaked guard file.
+fn reclaim_stale_trial_lock() -> bool {
+    let guard_path = format!("{}.reclaiming", CB_TRIAL_LOCK_PATH);
+
+    if try_create_reclaim_guard(&guard_path) {
+        return finish_reclaim(&guard_path);
+    }
+
+    let guard_stale = std::fs::metadata(&guard_path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map(|age| age.as_millis() as u64 >= CB_TRIAL_RECLAIM_GUARD_STALE_MS)
+        .unwrap_or(true);
+
+    if !guard_stale {
+        return false;
+    }
+    std::fs::remove_file(&guard_path).ok();
+    if !try_create_reclaim_guard(&guard_path) {
+        return false;
+    }
+    finish_reclaim(&guard_path)
+}
+
+fn try_create_reclaim_guard(guard_path: &str) -> bool {
+    OpenOptions::new().write(true).create_new(true).open(guard_path).is_ok()
+}
+
+fn finish_reclaim(guard_path: &str) -> bool {
+    // The owner may have released the lock normally (not crashed) while we
+    // waited for the guard, in which case it's simply gone rather than
+    // still present-but-stale; either way, remove_file's result doesn't
+    // matter here, only that nothing is left to block try_create_trial_lock.
+    let won = if is_trial_lock_stale() {
+        std::fs::remove_file(CB_TRIAL_LOCK_PATH).ok();
+        try_create_trial_lock()
+    } else {
+        false
+    };
+
+    std::fs::remove_file(guard_path).ok();
+    won
+}
+
+fn release_half_open_trial() {
+    std::fs::remove_file(CB_TRIAL_LOCK_PATH).ok();
+}
+
+fn cb_open_cooldown_elapsed(opened_at: u64) -> bool {
+    now_ms().saturating_sub(opened_at) >= cb_cooldown_ms()
+}
+
+/// Read-only check for whether it's worth spooling an event at all: only a
+/// hard Open still inside its cooldown blocks spooling. Half-Open is left
+/// entirely to the flusher (via `is_circuit_breaker_open`, called right
+/// before it sends), which is the only place a trial outcome can be resolved.
+fn is_circuit_breaker_blocking_spool() -> bool {
+    let data = read_cb_state();
+    matches!(data.state, BreakerState::Open) && !cb_open_cooldown_elapsed(data.opened_at)
+}
+
+fn is_circuit_breaker_open() -> bool {
+    let mut data = read_cb_state();
+
+    match data.state {
+        BreakerState::Closed => false,
+        BreakerState::Open => {
+            if cb_open_cooldown_elapsed(data.opened_at) {
+                data.state = BreakerState::HalfOpen;
+                write_cb_state(&data);
+                !claim_half_open_trial()
+            } else {
+                true
+            }
+        }
+        BreakerState::HalfOpen => !claim_half_open_trial(),
+    }
+}
+
+fn record_success() {
+    let data = read_cb_state();
+    if data.state != BreakerState::Closed {
+        release_half_open_trial();
+    }
+    write_cb_state(&CircuitBreakerData::default());
+}
+
+fn record_failure() {
+    let mut data = read_cb_state();
+
+    match data.state {
+        BreakerState::HalfOpen => {
+            release_half_open_trial();
+            data.state = BreakerState::Open;
+            data.opened_at = now_ms();
+        }
+        _ => {
+            data.consecutive_failures += 1;
+            if data.consecutive_failures >= cb_threshold() {
+                data.state = BreakerState::Open;
+                data.opened_at = now_ms();
+            }
+        }
+    }
+
+    write_cb_state(&data);
+}
+
+// --- Durable per-run event log -------------------------------------------
+//
+// Every event is fsync-appended as a length-prefixed frame to a per-run log
+// file. A separate flusher process (spawned by the hook, or invoked directly
+// via `--flush <run_id>`) tails that log from a persisted byte offset, sends
+// records in order, and only advances the offset on HTTP 2xx. This makes
+// delivery at-least-once: it survives both a failed send and the hook
+// process exiting, because the next invocation (or the still-running
+// flusher) picks up from the same offset.
+
+const FLUSHER_POLL_INTERVAL_MS: u64 = 200;
+const FLUSHER_MAX_IDLE_POLLS: u32 = 300; // ~60s of no growth and no sentinel
+
+fn sanitize_run_id(run_id: &str) -> String {
+    run_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+fn log_path_for(run_id: &str) -> String {
+    format!("/tmp/alpine_event_log_{}.bin", sanitize_run_id(run_id))
+}
+
+fn offset_path_for(run_id: &str) -> String {
+    format!("/tmp/alpine_event_log_{}.offset", sanitize_run_id(run_id))
+}
+
+fn pid_path_for(run_id: &str) -> String {
+    format!("/tmp/alpine_event_flusher_{}.pid", sanitize_run_id(run_id))
+}
+
+/// Appends one length-prefixed, fsync'd frame to the run's durable log.
+fn append_record(run_id: &str, record: &LogRecord, encoding: Encoding) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = encoding.encode(record)?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path_for(run_id))?;
+
+    file.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Reads every complete frame available from `start_offset` onward. An
+/// incomplete trailing frame (still being written) is left for the next
+/// poll rather than treated as an error.
+fn read_frames_from(log_path: &str, start_offset: u64, encoding: Encoding) -> io::Result<Vec<(u64, LogRecord)>> {
+    let mut file = File::open(log_path)?;
+    file.seek(SeekFrom::Start(start_offset))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    let mut records = Vec::new();
+    let mut cursor = 0usize;
+
+    while cursor + 4 <= buf.len() {
+        let len = u32::from_be_bytes(buf[cursor..cursor + 4].try_into().unwrap()) as usize;
+        if cursor + 4 + len > buf.len() {
+            break;
+        }
+        let frame_start = cursor + 4;
+        let frame_end = frame_start + len;
+        if let Ok(record) = encoding.decode::<LogRecord>(&buf[frame_start..frame_end]) {
+            records.push((start_offset + frame_end as u64, record));
+        }
+        cursor = frame_end;
+    }
+
+    Ok(records)
+}
+
+fn read_offset(offset_path: &str) -> u64 {
+    std::fs::read_to_string(offset_path)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn write_offset(offset_path: &str, offset: u64) -> io::Result<()> {
+    let tmp_path = format!("{}.tmp", offset_path);
+    {
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(offset.to_string().as_bytes())?;
+        tmp.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, offset_path)
+}
+
+/// Spawns a detached `--flush` subprocess for `run_id` unless one is already
+/// running, tracked via a pid file checked against `/proc`.
+fn ensure_flusher_running(run_id: &str) {
+    let pid_path = pid_path_for(run_id);
+
+    if let Ok(pid) = std::fs::read_to_string(&pid_path) {
+        if Path::new(&format!("/proc/{}", pid.trim())).exists() {
+            return;
+        }
+    }
+
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(_) => return,
+    };
+
+    match Command::new(exe)
+        .arg("--flush")
+        .arg(run_id)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => {
+            let _ = std::fs::write(&pid_path, child.id().to_string());
+        }
+        Err(e) => eprintln!("Failed to spawn event log flusher: {}", e),
+    }
+}
+
+/// Tails the run's durable log from its persisted offset, sending records in
+/// order and advancing the offset only on success, until it reads and
+/// acknowledges the terminal `RunFinished` record (or goes idle for too long
+/// with no growth, as a backstop against a sentinel that never arrives).
+///
+/// This is the run's sole delivery path (the hook itself only appends), so
+/// `ALPINE_TOOL_CALL_BATCH_SIZE` is applied here: consecutive `Event` records
+/// are grouped up to that size and sent with one `send_batch` call rather
+/// than one `send_event` per record.
+fn run_flusher(run_id: &str) -> io::Result<()> {
+    let _ = std::fs::write(pid_path_for(run_id), std::process::id().to_string());
+
+    let endpoint = match env::var("ALPINE_EVENTS_ENDPOINT") {
+        Ok(val) => val,
+        Err(_) => return Ok(()),
+    };
+    let encoding = Encoding::from_env();
+    let batch_size: usize = env::var("ALPINE_TOOL_CALL_BATCH_SIZE")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .unwrap_or(10)
+        .max(1);
+    let log_path = log_path_for(run_id);
+    let offset_path = offset_path_for(run_id);
+    let mut idle_polls = 0u32;
+
+    loop {
+        if !Path::new(&log_path).exists() {
+            break;
+        }
+
+        let offset = read_offset(&offset_path);
+        let records = read_frames_from(&log_path, offset, encoding)?;
+
+        if records.is_empty() {
+            idle_polls += 1;
+            if idle_polls >= FLUSHER_MAX_IDLE_POLLS {
+                // A hook can append a record in the narrow window between
+                // the read above and here while our pid file - the thing
+                // ensure_flusher_running checks before deciding whether to
+                // spawn a replacement - is still in place. Re-check right
+                // here, immediately before we'd hand off to compact_log,
+                // and keep running instead of losing that record: exiting
+                // now would both never process it and delete the log it's
+                // sitting in out from under it.
+                if !read_frames_from(&log_path, offset, encoding)?.is_empty() {
+                    idle_polls = 0;
+                    continue;
+                }
+                break;
+            }
+            thread::sleep(Duration::from_millis(FLUSHER_POLL_INTERVAL_MS));
+            continue;
+        }
+        idle_polls = 0;
+
+        // Take the next run of Event records (up to batch_size), stopping
+        // early at a RunFinished sentinel so it's never folded into a batch.
+        let mut group: Vec<(u64, &AgUIEvent)> = Vec::new();
+        let mut sentinel_offset = None;
+        for (end_offset, record) in &records {
+            match record {
+                LogRecord::Event(event) => {
+                    group.push((*end_offset, event));
+                    if group.len() >= batch_size {
+                        break;
+                    }
+                }
+                LogRecord::RunFinished => {
+                    sentinel_offset = Some(*end_offset);
+                    break;
+                }
+            }
+        }
+
+        if !group.is_empty() {
+            if is_circuit_breaker_open() {
+                eprintln!("Flusher: circuit breaker open, pausing at offset {}", offset);
+                thread::sleep(Duration::from_millis(FLUSHER_POLL_INTERVAL_MS));
+                continue;
+            }
+
+            let events: Vec<AgUIEvent> = group.iter().map(|(_, e)| (*e).clone()).collect();
+            let send_result = if events.len() > 1 {
+                send_batch(&endpoint, &events, encoding)
+            } else {
+                send_event(&endpoint, &events[0], encoding)
+            };
+
+            match send_result {
+                Ok(_) => {
+                    record_success();
+                    let (last_offset, _) = group.last().expect("group is non-empty");
+                    write_offset(&offset_path, *last_offset)?;
+                }
+                Err(e) => {
+                    record_failure();
+                    eprintln!("Flusher: send failed ({}), will retry from offset {}", e, offset);
+                    thread::sleep(Duration::from_millis(FLUSHER_POLL_INTERVAL_MS));
+                    continue;
+                }
+            }
+        }
+
+        if let Some(finish_offset) = sentinel_offset {
+            write_offset(&offset_path, finish_offset)?;
+            break;
+        }
+    }
+
+    compact_log(run_id);
+    std::fs::remove_file(pid_path_for(run_id)).ok();
+    Ok(())
+}
+
+/// Appends the terminal sentinel and synchronously drains whatever is left,
+/// so callers that invoke `--finish` at the end of a run block until the
+/// log is fully delivered and compacted.
+fn finish_run(run_id: &str) -> io::Result<()> {
+    let encoding = Encoding::from_env();
+    if let Err(e) = append_record(run_id, &LogRecord::RunFinished, encoding) {
+        eprintln!("Failed to append run-finished sentinel: {}", e);
+    }
+    run_flusher(run_id)
+}
+
+fn compact_log(run_id: &str) {
+    std::fs::remove_file(log_path_for(run_id)).ok();
+    std::fs::remove_file(offset_path_for(run_id)).ok();
+}
+
+// --- Workload-replay benchmark --------------------------------------------
+//
+// `bench <workload.json>...` drives each workload's synthetic tool-call
+// records through the real add_to_batch/send_event path so maintainers can
+// measure throughput and the effect of batching/sampling/encoding changes,
+// and gate regressions in CI. One result object is printed per workload file.
+
+fn run_bench(paths: &[String]) -> io::Result<()> {
+    for path in paths {
+        let contents = std::fs::read_to_string(path)?;
+        let workload: Workload = match serde_json::from_str(&contents) {
+            Ok(w) => w,
+            Err(e) => {
+                eprintln!("Failed to parse workload {}: {}", path, e);
+                continue;
+            }
+        };
+
+        let workload_name = workload.name.clone().unwrap_or_else(|| path.clone());
+        let result = run_one_workload(&workload, &workload_name);
+
+        println!("{}", serde_json::to_string(&result).unwrap_or_default());
+
+        if let Some(results_endpoint) = &workload.results_endpoint {
+            if let Err(e) = post_bench_result(results_endpoint, &result) {
+                eprintln!("Failed to post bench result for {}: {}", workload_name, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_one_workload(workload: &Workload, workload_name: &str) -> BenchResult {
+    let encoding = workload
+        .encoding
+        .as_deref()
+        .map(Encoding::from_name)
+        .unwrap_or_else(Encoding::from_env);
+    let endpoint = workload
+        .endpoint
+        .clone()
+        .or_else(|| env::var("ALPINE_EVENTS_ENDPOINT").ok())
+        .unwrap_or_default();
+    let batch_size = workload.batch_size.unwrap_or(1);
+    let sample_rate = workload.sample_rate.unwrap_or(100);
+    let interval = workload
+        .target_qps
+        .filter(|qps| *qps > 0.0)
+        .map(|qps| Duration::from_secs_f64(1.0 / qps));
+    let run_id = format!("bench-{}", workload_name);
+
+    let mut latencies_ms = Vec::new();
+    let mut bytes_sent = 0u64;
+    let mut failures = 0usize;
+    let mut circuit_breaker_trips = 0usize;
+
+    for tool_data in &workload.events {
+        if let Some(interval) = interval {
+            thread::sleep(interval);
+        }
+
+        // A read-only check first: no point building or queueing an event
+        // at all while the breaker is hard Open. The claim that actually
+        // resolves a Half-Open trial only happens right before a real send
+        // is attempted, below - never just for this skip decision.
+        if is_circuit_breaker_blocking_spool() {
+            failures += 1;
+            continue;
+        }
+
+        if sample_rate < 100 {
+            let mut rng = rand::thread_rng();
+            if rng.gen_range(1..=100) > sample_rate {
+                continue;
+            }
+        }
+
+        let tool_call_id = tool_data
+            .tool_call_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let event_type = if tool_data.tool_output.is_some() { "ToolCallEnd" } else { "ToolCallStart" };
+        let event = AgUIEvent {
+            event_type: event_type.to_string(),
+            data: EventData {
+                tool_call_id,
+                tool_call_name: tool_data.tool_name.clone(),
+                run_id: run_id.clone(),
+                tool_input: if event_type == "ToolCallStart" { tool_data.tool_input.clone() } else { None },
+                tool_output: if event_type == "ToolCallEnd" { tool_data.tool_output.clone() } else { None },
+            },
+        };
+
+        bytes_sent += encoding.encode(&event).map(|b| b.len() as u64).unwrap_or(0);
+
+        let start = Instant::now();
+        let send_outcome = if batch_size > 1 {
+            match add_to_batch(&run_id, &event, batch_size, &endpoint, encoding) {
+                Ok(outcome) => outcome,
+                Err(_) if is_circuit_breaker_open() => BatchOutcome::Blocked,
+                Err(_) => BatchOutcome::Sent(send_event(&endpoint, &event, encoding)),
+            }
+        } else if is_circuit_breaker_open() {
+            BatchOutcome::Blocked
+        } else {
+            BatchOutcome::Sent(send_event(&endpoint, &event, encoding))
+        };
+
+        // Only an event that actually triggered a send gets a latency sample
+        // and a breaker record_success/record_failure: recording a merely
+        // queued (`NotSent`) or breaker-rejected (`Blocked`) event that way
+        // would report a delivery that never happened. A `Blocked` event
+        // still counts toward the result's `failures`.
+        match send_outcome {
+            BatchOutcome::NotSent => {}
+            BatchOutcome::Sent(result) => {
+                latencies_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+                record_bench_send_outcome(result, &mut failures, &mut circuit_breaker_trips);
+            }
+            BatchOutcome::Blocked => failures += 1,
+        }
+    }
+
+    // A trailing batch that never reached batch_size is still sitting on
+    // disk at this point; flush it so it isn't silently dropped.
+    // flush_remaining_batch itself respects the breaker, same as every send
+    // attempt above.
+    if batch_size > 1 {
+        match flush_remaining_batch(&run_id, &endpoint, encoding) {
+            BatchOutcome::NotSent => {}
+            BatchOutcome::Sent(result) => record_bench_send_outcome(result, &mut failures, &mut circuit_breaker_trips),
+            BatchOutcome::Blocked => failures += 1,
+        }
+    }
+
+    BenchResult {
+        workload: workload_name.to_string(),
+        count: workload.events.len(),
+        p50_ms: percentile(&latencies_ms, 50.0),
+        p95_ms: percentile(&latencies_ms, 95.0),
+        p99_ms: percentile(&latencies_ms, 99.0),
+        bytes_sent,
+        failures,
+        circuit_breaker_trips,
+    }
+}
+
+/// Called only right after a real send was attempted (never for a merely
+/// queued or breaker-blocked event), so a trip is newly caused by this
+/// failure rather than something `record_bench_send_outcome`'s caller
+/// already filtered out upstream.
+fn record_bench_send_outcome(result: SendResult, failures: &mut usize, circuit_breaker_trips: &mut usize) {
+    match result {
+        Ok(_) => record_success(),
+        Err(e) => {
+            eprintln!("Failed to send event(s): {}", e);
+            record_failure();
+            *failures += 1;
+            if is_circuit_breaker_blocking_spool() {
+                *circuit_breaker_trips += 1;
+            }
+        }
+    }
+}
+
+fn percentile(latencies_ms: &[f64], p: f64) -> f64 {
+    if latencies_ms.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = latencies_ms.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let rank = ((p / 100.0) * (sorted.len() as f64 - 1.0)).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+fn post_bench_result(endpoint: &str, result: &BenchResult) -> Result<(), Box<dyn std::error::Error>> {
     let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(5))
+        .timeout(Duration::from_secs(10))
         .build()?;
-    
-    let response = client
-        .post(endpoint)
-        .json(event)
-        .send()?;
-    
+
+    let response = client.post(endpoint).json(result).send()?;
     if !response.status().is_success() {
         return Err(format!("HTTP error: {}", response.status()).into());
     }
-    
+
     Ok(())
-}
\ No newline at end of file
+}
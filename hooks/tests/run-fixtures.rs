@@ -0,0 +1,300 @@
+#!/usr/bin/env rust-script
+//! ```cargo
+//! [dependencies]
+//! serde = { version = "1.0", features = ["derive"] }
+//! serde_json = "1.0"
+//! rmp-serde = "1.1"
+//! regex = "1"
+//! ```
+//!
+//! Declarative JSON-fixture test harness for `alpine-ag-ui-emitter.rs`.
+//!
+//! Each fixture under `fixtures/*.json` supplies the hook's stdin, the
+//! environment variables it runs under, and the expected outcome: the set
+//! of `AgUIEvent`s it should emit (with `toolCallId` allowed to be `"*"` to
+//! match a generated UUID), its exit status, and regexes that must each
+//! match some stderr line. The harness spins up a tiny local HTTP capture
+//! server to stand in for `ALPINE_EVENTS_ENDPOINT`, runs the real hook
+//! binary against each fixture, and asserts the captured payloads and
+//! stderr against the fixture's expectations.
+//!
+//! Usage: `rust-script run-fixtures.rs [fixtures_dir] [hook_path]`
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Deserialize)]
+struct Fixture {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    stdin: Option<Value>,
+    #[serde(default)]
+    stdin_sequence: Vec<Value>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    setup_files: HashMap<String, Value>,
+    expect: Expectation,
+}
+
+#[derive(Debug, Deserialize)]
+struct Expectation {
+    #[serde(default)]
+    exit_code: i32,
+    #[serde(default)]
+    events: Vec<Value>,
+    #[serde(default)]
+    stderr_matches: Vec<String>,
+    #[serde(default)]
+    files_exist: Vec<String>,
+    #[serde(default)]
+    files_absent: Vec<String>,
+}
+
+// Breaker state the hook shares across process invocations via /tmp, cleared
+// before every fixture so one fixture's breaker state can't leak into the
+// next. The hook does not write `/tmp/alpine_event_batch.json`; its queued
+// events live in a durable log keyed by run id, which is not cleared here,
+// so each fixture sets its own `ALPINE_RUN_ID`.
+const GLOBAL_STATE_PATHS: &[&str] = &[
+    "/tmp/alpine_circuit_breaker_state.json",
+    "/tmp/alpine_circuit_breaker_trial.lock",
+    "/tmp/alpine_event_batch.json",
+];
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let fixtures_dir = args.next().unwrap_or_else(|| "hooks/tests/fixtures".to_string());
+    let hook_path = args.next().unwrap_or_else(|| "hooks/alpine-ag-ui-emitter.rs".to_string());
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&fixtures_dir)
+        .unwrap_or_else(|e| panic!("cannot read fixtures dir {}: {}", fixtures_dir, e))
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    let mut failures = 0;
+    for path in &paths {
+        match run_fixture(path, &hook_path) {
+            Ok(name) => println!("ok   {}", name),
+            Err(e) => {
+                failures += 1;
+                println!("FAIL {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    println!("{} fixtures, {} failed", paths.len(), failures);
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn run_fixture(path: &Path, hook_path: &str) -> Result<String, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let fixture: Fixture = serde_json::from_str(&contents).map_err(|e| format!("invalid fixture: {}", e))?;
+    let name = if fixture.name.is_empty() { path.display().to_string() } else { fixture.name.clone() };
+
+    for state_path in GLOBAL_STATE_PATHS {
+        std::fs::remove_file(state_path).ok();
+    }
+    for (file_path, value) in &fixture.setup_files {
+        let serialized = serde_json::to_string(value).map_err(|e| e.to_string())?;
+        std::fs::write(file_path, serialized).map_err(|e| format!("setup_files write {}: {}", file_path, e))?;
+    }
+
+    let encoding = fixture.env.get("ALPINE_EVENTS_ENCODING").map(String::as_str).unwrap_or("json");
+    let (port, captured) = start_capture_server(encoding);
+    let endpoint = format!("http://127.0.0.1:{}/events", port);
+
+    let inputs: Vec<Value> = if !fixture.stdin_sequence.is_empty() {
+        fixture.stdin_sequence.clone()
+    } else {
+        vec![fixture.stdin.clone().ok_or("fixture has neither stdin nor stdin_sequence")?]
+    };
+
+    let mut last_exit_code = 0;
+    let mut last_stderr = String::new();
+    for input in &inputs {
+        let (exit_code, stderr) = run_hook_once(hook_path, &endpoint, &fixture.env, input)?;
+        last_exit_code = exit_code;
+        last_stderr = stderr;
+    }
+
+    if last_exit_code != fixture.expect.exit_code {
+        return Err(format!("exit code {} != expected {}", last_exit_code, fixture.expect.exit_code));
+    }
+
+    for pattern in &fixture.expect.stderr_matches {
+        let re = Regex::new(pattern).map_err(|e| format!("bad regex {:?}: {}", pattern, e))?;
+        if !last_stderr.lines().any(|line| re.is_match(line)) {
+            return Err(format!("stderr did not match /{}/, got:\n{}", pattern, last_stderr));
+        }
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        let seen = captured.lock().unwrap().clone();
+        let all_found = fixture
+            .expect
+            .events
+            .iter()
+            .all(|expected| seen.iter().any(|actual| json_matches(expected, actual)));
+        if all_found {
+            break;
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("expected events not captured; got: {:?}", seen));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    // Delivery is async (the hook only appends to the durable log; the
+    // flusher does the actual sending), so settle a bit longer and then
+    // require the capture count to match exactly. This is the harness's
+    // regression guard against double-delivery: a flusher that re-sends a
+    // record the hot path already delivered would show up as extra captures
+    // here even though `all_found` above was already satisfied.
+    thread::sleep(Duration::from_millis(500));
+    let settled = captured.lock().unwrap().clone();
+    if settled.len() != fixture.expect.events.len() {
+        return Err(format!(
+            "expected exactly {} event(s), got {}: {:?}",
+            fixture.expect.events.len(),
+            settled.len(),
+            settled
+        ));
+    }
+
+    for file_path in &fixture.expect.files_exist {
+        if !Path::new(file_path).exists() {
+            return Err(format!("expected file {} to exist", file_path));
+        }
+    }
+    for file_path in &fixture.expect.files_absent {
+        if Path::new(file_path).exists() {
+            return Err(format!("expected file {} to be absent", file_path));
+        }
+    }
+
+    Ok(name)
+}
+
+fn run_hook_once(
+    hook_path: &str,
+    endpoint: &str,
+    env: &HashMap<String, String>,
+    stdin_value: &Value,
+) -> Result<(i32, String), String> {
+    let mut child = Command::new("rust-script")
+        .arg(hook_path)
+        .env("ALPINE_EVENTS_ENDPOINT", endpoint)
+        .envs(env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to spawn hook: {}", e))?;
+
+    {
+        let stdin = child.stdin.as_mut().ok_or("failed to open hook stdin")?;
+        stdin
+            .write_all(serde_json::to_string(stdin_value).map_err(|e| e.to_string())?.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    let exit_code = output.status.code().unwrap_or(-1);
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    Ok((exit_code, stderr))
+}
+
+/// Structural match: every key in `expected` must be present and equal in
+/// `actual` (extra keys in `actual` are ignored), and the string `"*"`
+/// matches any value — used for the UUID-generated `toolCallId`.
+fn json_matches(expected: &Value, actual: &Value) -> bool {
+    match expected {
+        Value::String(s) if s == "*" => true,
+        Value::Object(expected_map) => match actual {
+            Value::Object(actual_map) => expected_map
+                .iter()
+                .all(|(k, v)| actual_map.get(k).is_some_and(|av| json_matches(v, av))),
+            _ => false,
+        },
+        Value::Array(expected_items) => match actual {
+            Value::Array(actual_items) => {
+                expected_items.len() == actual_items.len()
+                    && expected_items.iter().zip(actual_items).all(|(e, a)| json_matches(e, a))
+            }
+            _ => false,
+        },
+        other => other == actual,
+    }
+}
+
+/// Minimal HTTP/1.1 capture server: accepts POSTed event bodies, decodes
+/// them per `encoding` (mirroring `ALPINE_EVENTS_ENCODING`), and flattens
+/// `{"events": [...]}` batch payloads so `captured` always holds individual
+/// events regardless of whether they arrived via `send_event` or `send_batch`.
+fn start_capture_server(encoding: &str) -> (u16, Arc<Mutex<Vec<Value>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind capture server");
+    let port = listener.local_addr().expect("capture server addr").port();
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let captured_for_thread = captured.clone();
+    let encoding = encoding.to_string();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            if let Some(body) = read_http_body(&mut stream) {
+                let decoded = match encoding.as_str() {
+                    "msgpack" => rmp_serde::from_slice::<Value>(&body).ok(),
+                    _ => serde_json::from_slice::<Value>(&body).ok(),
+                };
+                if let Some(value) = decoded {
+                    let mut guard = captured_for_thread.lock().unwrap();
+                    match value.get("events").and_then(Value::as_array) {
+                        Some(events) => guard.extend(events.iter().cloned()),
+                        None => guard.push(value),
+                    }
+                }
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            }
+        }
+    });
+
+    (port, captured)
+}
+
+fn read_http_body(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut content_length = 0usize;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line).ok()?;
+        if n == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+        if let Some(rest) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+            content_length = rest.trim().parse().unwrap_or(0);
+        }
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).ok()?;
+    Some(body)
+}